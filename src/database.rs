@@ -0,0 +1,24 @@
+//! The pooled async database connection shared by every resolver.
+
+use diesel_async::pooled_connection::deadpool::Pool as DieselPool;
+use diesel_async::pooled_connection::AsyncDieselConnectionManager;
+use diesel_async::AsyncPgConnection;
+
+use crate::config_variables::{DATABASE_POOL_SIZE, DATABASE_URL};
+
+/// Pooled async Postgres connection, built once at startup and registered
+/// on the GraphQL context so resolvers borrow a connection from the pool
+/// instead of each opening their own.
+pub type DbPool = DieselPool<AsyncPgConnection>;
+
+/// Builds the pool, sized from `DATABASE_POOL_SIZE` so concurrent database
+/// usage under load stays bounded rather than opening one connection per
+/// in-flight request.
+pub fn build_pool() -> DbPool {
+    let manager = AsyncDieselConnectionManager::<AsyncPgConnection>::new(DATABASE_URL);
+
+    DieselPool::builder(manager)
+        .max_size(DATABASE_POOL_SIZE)
+        .build()
+        .expect("failed to build the database connection pool")
+}
@@ -0,0 +1,55 @@
+//! Apollo Federation entity resolvers for the types this subgraph owns
+//! outright.
+//!
+//! `Person` and `Team` get the same treatment as `RoleQuery::find_role_by_id`:
+//! a plain `#[Object]` query fragment with a `#[graphql(entity)]` finder,
+//! no `extends`/`external` (this subgraph has the full table for both, so
+//! there's no other subgraph to extend). Merge these into the schema's
+//! query root alongside `RoleQuery`, as `graphql::QueryRoot` does.
+//!
+//! `Person`/`Team` aren't defined in this cut of the tree, so they don't
+//! get an `id()` resolver added here the way `Role::id` was -- that needs
+//! to land in their own `#[Object]` impls (wherever `person.rs`/`team.rs`
+//! live) before the gateway can actually round-trip the federation key.
+
+use async_graphql::{Context, Object, Result};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use uuid::Uuid;
+
+use crate::database::DbPool;
+use crate::graphql::graphql_translate;
+use crate::models::{Person, Team};
+use crate::schema::{persons, teams};
+
+#[derive(Default)]
+pub struct PersonQuery;
+
+#[Object]
+impl PersonQuery {
+    #[graphql(entity)]
+    async fn find_person_by_id(&self, ctx: &Context<'_>, id: Uuid) -> Result<Person> {
+        let pool = ctx.data::<DbPool>()?;
+        let mut conn = pool.get().await?;
+
+        let res = persons::table.filter(persons::id.eq(id)).first(&mut conn).await;
+
+        graphql_translate(res)
+    }
+}
+
+#[derive(Default)]
+pub struct TeamQuery;
+
+#[Object]
+impl TeamQuery {
+    #[graphql(entity)]
+    async fn find_team_by_id(&self, ctx: &Context<'_>, id: Uuid) -> Result<Team> {
+        let pool = ctx.data::<DbPool>()?;
+        let mut conn = pool.get().await?;
+
+        let res = teams::table.filter(teams::id.eq(id)).first(&mut conn).await;
+
+        graphql_translate(res)
+    }
+}
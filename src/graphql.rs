@@ -0,0 +1,39 @@
+//! GraphQL schema assembly: the loaders and pool get registered on the
+//! context here, rather than leaving that step for whoever wires up main().
+
+use async_graphql::dataloader::DataLoader;
+use async_graphql::{EmptyMutation, EmptySubscription, Error as GraphQLError, MergedObject, Result as GraphQLResult, Schema};
+use diesel::result::Error as DieselError;
+
+use crate::database::DbPool;
+use crate::entities::{PersonQuery, TeamQuery};
+use crate::loaders::{PersonLoader, TeamLoader};
+use crate::models::role::RoleQuery;
+
+/// Converts a Diesel result into the `async_graphql::Result` every
+/// resolver in this crate returns.
+pub fn graphql_translate<T>(res: Result<T, DieselError>) -> GraphQLResult<T> {
+    res.map_err(|e| GraphQLError::new(e.to_string()))
+}
+
+/// Each model contributes its own query fragment (entity resolvers,
+/// aggregates, ...), merged into one root so a request's schema surface
+/// stays reviewable on its own instead of growing one big struct.
+#[derive(MergedObject, Default)]
+pub struct QueryRoot(RoleQuery, PersonQuery, TeamQuery);
+
+/// Placeholder until the rest of the crate's mutation root is pulled into
+/// this cut of the tree; the real app merges its own `MutationRoot` here.
+pub type AppSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Builds the schema once at startup: constructs the pool-backed
+/// `DataLoader`s and registers both them and the raw pool on the context,
+/// so every resolver that needs the database pulls it from there instead
+/// of a global singleton.
+pub fn build_schema(pool: DbPool) -> AppSchema {
+    Schema::build(QueryRoot::default(), EmptyMutation, EmptySubscription)
+        .data(DataLoader::new(PersonLoader { pool: pool.clone() }, tokio::spawn))
+        .data(DataLoader::new(TeamLoader { pool: pool.clone() }, tokio::spawn))
+        .data(pool)
+        .finish()
+}
@@ -0,0 +1,70 @@
+//! DataLoader implementations for batching relation lookups.
+//!
+//! Each loader holds the `DbPool` built once at startup. They're
+//! registered on the context by `graphql::build_schema`, alongside the raw
+//! pool itself (resolvers that need a connection directly -- `Role::history`,
+//! the federation entity finders -- pull `DbPool` from the context too).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_graphql::dataloader::Loader;
+use async_graphql::{Error, Result};
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use uuid::Uuid;
+
+use crate::database::DbPool;
+use crate::graphql::graphql_translate;
+use crate::models::{Person, Team};
+use crate::schema::{persons, teams};
+
+/// Batches `Person` lookups by id so relation resolvers (e.g. `Role::person`)
+/// issue one query per request instead of one per row.
+pub struct PersonLoader {
+    pub pool: DbPool,
+}
+
+#[async_trait::async_trait]
+impl Loader<Uuid> for PersonLoader {
+    type Value = Person;
+    type Error = Arc<Error>;
+
+    async fn load(&self, ids: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let mut conn = self.pool.get().await.map_err(|e| Arc::new(Error::new(e.to_string())))?;
+
+        let res = persons::table
+            .filter(persons::id.eq_any(ids))
+            .load::<Person>(&mut conn)
+            .await;
+
+        let persons = graphql_translate(res).map_err(Arc::new)?;
+
+        Ok(persons.into_iter().map(|p| (p.id, p)).collect())
+    }
+}
+
+/// Batches `Team` lookups by id so relation resolvers (e.g. `Role::team`)
+/// issue one query per request instead of one per row.
+pub struct TeamLoader {
+    pub pool: DbPool,
+}
+
+#[async_trait::async_trait]
+impl Loader<Uuid> for TeamLoader {
+    type Value = Team;
+    type Error = Arc<Error>;
+
+    async fn load(&self, ids: &[Uuid]) -> Result<HashMap<Uuid, Self::Value>, Self::Error> {
+        let mut conn = self.pool.get().await.map_err(|e| Arc::new(Error::new(e.to_string())))?;
+
+        let res = teams::table
+            .filter(teams::id.eq_any(ids))
+            .load::<Team>(&mut conn)
+            .await;
+
+        let teams = graphql_translate(res).map_err(Arc::new)?;
+
+        Ok(teams.into_iter().map(|t| (t.id, t)).collect())
+    }
+}
@@ -3,15 +3,20 @@ use std::fmt::Debug;
 use chrono::{prelude::*};
 use serde::{Deserialize, Serialize};
 use diesel::{self, Insertable, Queryable, ExpressionMethods};
-use diesel::{RunQueryDsl, QueryDsl};
+use diesel::{BoolExpressionMethods, TextExpressionMethods};
+use diesel::QueryDsl;
+use diesel_async::{AsyncConnection, AsyncPgConnection, RunQueryDsl};
 use uuid::Uuid;
 use async_graphql::*;
 
+use async_graphql::dataloader::DataLoader;
+
 use crate::graphql::graphql_translate;
-use crate::config_variables::DATE_FORMAT;
+use crate::config_variables::{DATE_FORMAT, MAX_TOTAL_EFFORT};
+use crate::loaders::{PersonLoader, TeamLoader};
 
 use crate::schema::*;
-use crate::database::connection;
+use crate::database::DbPool;
 
 use super::{Person, Team};
 
@@ -35,12 +40,28 @@ pub struct Role {
 
 #[Object]
 impl Role {
-    pub async fn person(&self) -> Result<Person> {
-        Person::get_by_id(&self.person_id)
+    /// The federation key this subgraph publishes. `Role` is owned here
+    /// (this crate has the full table and every field), so `id` is a plain
+    /// resolver — `extends`/`external` is only for a subgraph that's
+    /// merely adding fields to a type some other subgraph owns.
+    pub async fn id(&self) -> Result<Uuid> {
+        Ok(self.id)
+    }
+
+    pub async fn person(&self, ctx: &Context<'_>) -> Result<Person> {
+        let loader = ctx.data::<DataLoader<PersonLoader>>()?;
+        loader
+            .load_one(self.person_id)
+            .await?
+            .ok_or_else(|| Error::new("Person not found"))
     }
 
-    pub async fn team(&self) -> Result<Team> {
-        Team::get_by_id(&self.team_id)
+    pub async fn team(&self, ctx: &Context<'_>) -> Result<Team> {
+        let loader = ctx.data::<DataLoader<TeamLoader>>()?;
+        loader
+            .load_one(self.team_id)
+            .await?
+            .ok_or_else(|| Error::new("Team not found"))
     }
 
     pub async fn english_title(&self) -> Result<String> {
@@ -81,83 +102,476 @@ impl Role {
     pub async fn updated_at(&self) -> Result<String> {
         Ok(self.updated_at.format(DATE_FORMAT).to_string())
     }
+
+    /// The ordered chain of versions of this role, oldest first, including
+    /// the version currently in effect.
+    pub async fn history(&self, ctx: &Context<'_>) -> Result<Vec<RoleVersion>> {
+        let pool = ctx.data::<DbPool>()?;
+        let mut conn = pool.get().await?;
+
+        let versions = role_versions::table
+            .filter(role_versions::role_id.eq(self.id))
+            .order(role_versions::valid_from.asc())
+            .load::<RoleVersion>(&mut conn)
+            .await?;
+
+        Ok(versions)
+    }
 }
 
 
+/// Rejects an effort outside the `0.0..=1.0` range a single role may claim.
+fn validate_effort(effort: f64) -> Result<()> {
+    if !(0.0..=1.0).contains(&effort) {
+        return Err(Error::new("effort must be between 0.0 and 1.0")
+            .extend_with(|_, e| e.set("field", "effort")));
+    }
+    Ok(())
+}
+
+/// Rejects an `end_date` earlier than `start_datestamp`.
+fn validate_date_range(start: NaiveDateTime, end: Option<NaiveDateTime>) -> Result<()> {
+    if let Some(end) = end {
+        if end < start {
+            return Err(Error::new("end_date must not be before start_datestamp")
+                .extend_with(|_, e| e.set("field", "end_date")));
+        }
+    }
+    Ok(())
+}
+
 // Non Graphql
 impl Role {
-    pub fn create(role: &NewRole) -> Result<Role> {
-        let mut conn = connection()?;
+    /// Rejects a write that would push the sum of a person's active roles'
+    /// effort past `MAX_TOTAL_EFFORT`. `excluding` is the id of the role
+    /// being updated, so it isn't double-counted against itself. An inactive
+    /// role contributes nothing to the budget, since it can't be claiming
+    /// any of the person's time.
+    async fn ensure_effort_budget(conn: &mut AsyncPgConnection, person_id: Uuid, effort: f64, active: bool, excluding: Option<Uuid>) -> Result<()> {
+        if !active {
+            return Ok(());
+        }
+
+        // Postgres's default READ COMMITTED isolation doesn't lock rows read
+        // by a plain SELECT, so two concurrent create/update calls for the
+        // same person could otherwise both read the same pre-commit total
+        // and both pass. Take a transaction-scoped advisory lock keyed on
+        // person_id first, so concurrent callers serialize here and each
+        // sees the other's committed effort before checking the budget.
+        diesel::sql_query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+            .bind::<diesel::sql_types::Text, _>(person_id.to_string())
+            .execute(conn)
+            .await?;
 
-        let res = diesel::insert_into(roles::table)
-        .values(role)
-        .get_result(&mut conn);
-        
-        graphql_translate(res)
+        let existing = roles::table
+            .filter(roles::person_id.eq(person_id))
+            .load::<Role>(conn)
+            .await?;
+
+        let total: f64 = existing
+            .iter()
+            .filter(|r| r.active && Some(r.id) != excluding)
+            .map(|r| r.effort)
+            .sum::<f64>()
+            + effort;
+
+        if total > MAX_TOTAL_EFFORT {
+            return Err(Error::new(format!(
+                "total active effort for person would be {:.2}, exceeding the maximum of {:.2}",
+                total, MAX_TOTAL_EFFORT
+            ))
+            .extend_with(|_, e| e.set("field", "effort")));
+        }
+
+        Ok(())
     }
-    
-    pub fn get_or_create(role: &NewRole) -> Result<Role> {
-        let mut conn = connection()?;
+
+    pub async fn create(pool: &DbPool, role: &NewRole) -> Result<Role> {
+        validate_effort(role.effort)?;
+        validate_date_range(role.start_datestamp, role.end_date)?;
+
+        let mut conn = pool.get().await?;
+
+        // One transaction covers the effort-budget check *and* both writes
+        // below: besides closing the budget-check race, this is what keeps
+        // role_versions in sync with roles -- a crash between the two
+        // inserts can't leave a role on record with no opening version.
+        let created: Role = conn
+            .transaction(|conn| {
+                Box::pin(async move {
+                    Role::ensure_effort_budget(conn, role.person_id, role.effort, role.active, None).await?;
+
+                    let res = diesel::insert_into(roles::table)
+                        .values(role)
+                        .get_result(conn)
+                        .await;
+
+                    let created: Role = graphql_translate(res)?;
+
+                    diesel::insert_into(role_versions::table)
+                        .values(RoleVersion::snapshot(&created, created.created_at, None))
+                        .execute(conn)
+                        .await?;
+
+                    Ok::<_, Error>(created)
+                })
+            })
+            .await?;
+
+        Ok(created)
+    }
+
+    pub async fn get_or_create(pool: &DbPool, role: &NewRole) -> Result<Role> {
+        let mut conn = pool.get().await?;
 
         let res = roles::table
         .filter(roles::person_id.eq(&role.person_id))
         .distinct()
-        .first(&mut conn);
-        
+        .first(&mut conn)
+        .await;
+
         let role = match res {
             Ok(p) => p,
             Err(e) => {
                 // Role not found
                 println!("{:?}", e);
-                let p = Role::create(role).expect("Unable to create role");
-                p
+                Role::create(pool, role).await?
             }
         };
         Ok(role)
     }
 
-    pub fn find_all() -> Result<Vec<Self>> {
-        let mut conn = connection()?;
-        let roles = roles::table.load::<Role>(&mut conn)?;
+    pub async fn find_all(pool: &DbPool) -> Result<Vec<Self>> {
+        let mut conn = pool.get().await?;
+        let roles = roles::table.load::<Role>(&mut conn).await?;
         Ok(roles)
     }
 
-    pub fn get_by_id(id: Uuid) -> Result<Self> {
-        let mut conn = connection()?;
-        let role = roles::table.filter(roles::id.eq(id)).first(&mut conn)?;
+    pub async fn get_by_id(pool: &DbPool, id: Uuid) -> Result<Self> {
+        let mut conn = pool.get().await?;
+        let role = roles::table.filter(roles::id.eq(id)).first(&mut conn).await?;
         Ok(role)
     }
 
-    pub fn get_by_team_id(id: Uuid) -> Result<Vec<Role>> {
-        let mut conn = connection()?;
+    pub async fn get_by_team_id(pool: &DbPool, id: Uuid) -> Result<Vec<Role>> {
+        let mut conn = pool.get().await?;
 
         let res = roles::table
             .filter(roles::team_id.eq(id))
-            .load::<Role>(&mut conn)?;
+            .load::<Role>(&mut conn)
+            .await?;
 
         Ok(res)
     }
 
-    pub fn get_by_person_id(id: Uuid) -> Result<Vec<Role>> {
-        let mut conn = connection()?;
+    pub async fn get_by_person_id(pool: &DbPool, id: Uuid) -> Result<Vec<Role>> {
+        let mut conn = pool.get().await?;
 
         let res = roles::table
             .filter(roles::person_id.eq(id))
-            .load::<Role>(&mut conn)?;
+            .load::<Role>(&mut conn)
+            .await?;
 
         Ok(res)
     }
-    
-    pub fn update(&self) -> Result<Self> {
-        let mut conn = connection()?;
 
-        let res = diesel::update(roles::table)
-        .filter(roles::id.eq(&self.id))
-        .set(self)
-        .get_result(&mut conn)?;
-        
+    /// Updates the role in place and appends a new entry to its history
+    /// rather than overwriting it: the previously-current version is closed
+    /// off at `now`, and a fresh version is opened for the new state.
+    ///
+    /// All three writes (close the old version, update the role, open the
+    /// new version) share one transaction, so a crash mid-update can't
+    /// leave the role changed with no matching version, or a version
+    /// closed with nothing open to replace it.
+    pub async fn update(&self, pool: &DbPool) -> Result<Self> {
+        validate_effort(self.effort)?;
+        validate_date_range(self.start_datestamp, self.end_date)?;
+
+        let mut conn = pool.get().await?;
+
+        let res: Role = conn
+            .transaction(|conn| {
+                Box::pin(async move {
+                    Role::ensure_effort_budget(conn, self.person_id, self.effort, self.active, Some(self.id)).await?;
+
+                    let now = Utc::now().naive_utc();
+
+                    diesel::update(role_versions::table)
+                        .filter(role_versions::role_id.eq(&self.id))
+                        .filter(role_versions::valid_to.is_null())
+                        .set(role_versions::valid_to.eq(now))
+                        .execute(conn)
+                        .await?;
+
+                    let res: Role = diesel::update(roles::table)
+                        .filter(roles::id.eq(&self.id))
+                        .set(self)
+                        .get_result(conn)
+                        .await?;
+
+                    diesel::insert_into(role_versions::table)
+                        .values(RoleVersion::snapshot(&res, now, None))
+                        .execute(conn)
+                        .await?;
+
+                    Ok::<_, Error>(res)
+                })
+            })
+            .await?;
+
+        Ok(res)
+    }
+}
+
+impl From<RoleVersion> for Role {
+    fn from(v: RoleVersion) -> Self {
+        Role {
+            id: v.role_id,
+            person_id: v.person_id,
+            team_id: v.team_id,
+            title_en: v.title_en,
+            title_fr: v.title_fr,
+            effort: v.effort,
+            active: v.active,
+            start_datestamp: v.start_datestamp,
+            end_date: v.end_date,
+            created_at: v.valid_from,
+            updated_at: v.valid_from,
+        }
+    }
+}
+
+/// A closed-or-open snapshot of a `Role` row, capturing its full prior
+/// state plus the system-time interval (`valid_from`..`valid_to`) during
+/// which it was the current version. `valid_to: None` means this is the
+/// version currently in effect.
+#[derive(Debug, Clone, Deserialize, Serialize, Queryable, Insertable)]
+#[diesel(table_name = role_versions)]
+pub struct RoleVersion {
+    pub id: Uuid,
+    pub role_id: Uuid,
+    pub person_id: Uuid,
+    pub team_id: Uuid,
+    pub title_en: String,
+    pub title_fr: String,
+    pub effort: f64,
+    pub active: bool,
+    pub start_datestamp: NaiveDateTime,
+    pub end_date: Option<NaiveDateTime>,
+    pub valid_from: NaiveDateTime,
+    pub valid_to: Option<NaiveDateTime>,
+}
+
+impl RoleVersion {
+    fn snapshot(role: &Role, valid_from: NaiveDateTime, valid_to: Option<NaiveDateTime>) -> Self {
+        RoleVersion {
+            id: Uuid::new_v4(),
+            role_id: role.id,
+            person_id: role.person_id,
+            team_id: role.team_id,
+            title_en: role.title_en.clone(),
+            title_fr: role.title_fr.clone(),
+            effort: role.effort,
+            active: role.active,
+            start_datestamp: role.start_datestamp,
+            end_date: role.end_date,
+            valid_from,
+            valid_to,
+        }
+    }
+}
+
+#[Object]
+impl RoleVersion {
+    pub async fn role_id(&self) -> Result<Uuid> {
+        Ok(self.role_id)
+    }
+
+    pub async fn english_title(&self) -> Result<String> {
+        Ok(self.title_en.to_owned())
+    }
+
+    pub async fn french_title(&self) -> Result<String> {
+        Ok(self.title_fr.to_owned())
+    }
+
+    pub async fn effort(&self) -> Result<f64> {
+        Ok(self.effort)
+    }
+
+    pub async fn active(&self) -> Result<String> {
+        if self.active {
+            Ok("Active".to_string())
+        } else {
+            Ok("INACTIVE".to_string())
+        }
+    }
+
+    pub async fn start_date(&self) -> Result<String> {
+        Ok(self.start_datestamp.format(DATE_FORMAT).to_string())
+    }
+
+    pub async fn end_date(&self) -> Result<String> {
+        match self.end_date {
+            Some(d) => Ok(d.format(DATE_FORMAT).to_string()),
+            None => Ok("Still Active".to_string())
+        }
+    }
+
+    pub async fn valid_from(&self) -> Result<String> {
+        Ok(self.valid_from.format(DATE_FORMAT).to_string())
+    }
+
+    pub async fn valid_to(&self) -> Result<String> {
+        match self.valid_to {
+            Some(d) => Ok(d.format(DATE_FORMAT).to_string()),
+            None => Ok("Current".to_string())
+        }
+    }
+}
+
+/// Whether a version's system-time validity window contains `as_of`.
+/// `valid_to` is the exclusive end of the window: a version closed exactly
+/// at `as_of` (e.g. by an `update()` that ran at that same instant) is no
+/// longer current as of that instant.
+fn version_covers(valid_from: NaiveDateTime, valid_to: Option<NaiveDateTime>, as_of: NaiveDateTime) -> bool {
+    valid_from <= as_of && valid_to.map_or(true, |valid_to| valid_to > as_of)
+}
+
+/// Returns the roles whose validity window contained `as_of`, reconstructed
+/// from `role_versions` history. Exposed on the query root via
+/// `RoleQuery::roles_as_of` below.
+pub async fn roles_as_of(pool: &DbPool, as_of: NaiveDateTime) -> Result<Vec<Role>> {
+    let mut conn = pool.get().await?;
+
+    // Narrow in SQL on the indexed, always-applicable half of the window;
+    // the exclusive `valid_to` boundary is applied in Rust via
+    // `version_covers` so its semantics stay unit-testable.
+    let versions = role_versions::table
+        .filter(role_versions::valid_from.le(as_of))
+        .load::<RoleVersion>(&mut conn)
+        .await?;
+
+    Ok(versions
+        .into_iter()
+        .filter(|v| version_covers(v.valid_from, v.valid_to, as_of))
+        .map(Role::from)
+        .collect())
+}
+
+/// Query-root fragment contributing `Role`'s federation entity resolver
+/// and its regular top-level queries. Merged into the schema's query root
+/// alongside `PersonQuery`/`TeamQuery` by `graphql::QueryRoot`, so a
+/// gateway's `_entities` call can resolve a `Role` reference by `id`.
+#[derive(Default)]
+pub struct RoleQuery;
+
+#[Object]
+impl RoleQuery {
+    #[graphql(entity)]
+    async fn find_role_by_id(&self, ctx: &Context<'_>, id: Uuid) -> Result<Role> {
+        let pool = ctx.data::<DbPool>()?;
+        Role::get_by_id(pool, id).await
+    }
+
+    /// The roles whose validity window contained `as_of`, reconstructed
+    /// from `role_versions` history.
+    async fn roles_as_of(&self, ctx: &Context<'_>, as_of: NaiveDateTime) -> Result<Vec<Role>> {
+        let pool = ctx.data::<DbPool>()?;
+        roles_as_of(pool, as_of).await
+    }
+}
+
+/// Composable filter for `Role::find_by_filter`. Every field is optional and
+/// only the ones that are `Some` are applied, so clients can query as
+/// broadly or as narrowly as they need instead of relying on `find_all`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, InputObject)]
+pub struct RoleFilter {
+    pub team_id: Option<Uuid>,
+    pub person_id: Option<Uuid>,
+    pub active: Option<bool>,
+    pub min_effort: Option<f64>,
+    pub max_effort: Option<f64>,
+    /// Select roles active on this date, i.e. `start_datestamp <= active_on`
+    /// and (`end_date IS NULL OR end_date >= active_on`).
+    pub active_on: Option<NaiveDate>,
+    pub title_contains: Option<String>,
+}
+
+/// Total effort allocated to a team, for dashboard-style aggregate queries.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct TeamEffort {
+    pub team_id: Uuid,
+    pub total_effort: f64,
+}
+
+// Analytics / filtering
+impl Role {
+    pub async fn find_by_filter(pool: &DbPool, filter: &RoleFilter) -> Result<Vec<Self>> {
+        let mut conn = pool.get().await?;
+
+        let mut query = roles::table.into_boxed();
+
+        if let Some(team_id) = filter.team_id {
+            query = query.filter(roles::team_id.eq(team_id));
+        }
+        if let Some(person_id) = filter.person_id {
+            query = query.filter(roles::person_id.eq(person_id));
+        }
+        if let Some(active) = filter.active {
+            query = query.filter(roles::active.eq(active));
+        }
+        if let Some(min_effort) = filter.min_effort {
+            query = query.filter(roles::effort.ge(min_effort));
+        }
+        if let Some(max_effort) = filter.max_effort {
+            query = query.filter(roles::effort.le(max_effort));
+        }
+        if let Some(active_on) = filter.active_on {
+            let at = active_on.and_hms_opt(0, 0, 0).expect("valid midnight timestamp");
+            query = query
+                .filter(roles::start_datestamp.le(at))
+                .filter(roles::end_date.is_null().or(roles::end_date.ge(at)));
+        }
+        if let Some(title_contains) = &filter.title_contains {
+            query = query.filter(roles::title_en.like(format!("%{}%", title_contains)));
+        }
+
+        let res = query.load::<Role>(&mut conn).await?;
         Ok(res)
     }
+
+    /// Total effort summed per team, for dashboards.
+    pub async fn total_effort_by_team(pool: &DbPool) -> Result<Vec<TeamEffort>> {
+        let mut conn = pool.get().await?;
+
+        let res: Vec<(Uuid, Option<f64>)> = roles::table
+            .group_by(roles::team_id)
+            .select((roles::team_id, diesel::dsl::sum(roles::effort)))
+            .load(&mut conn)
+            .await?;
+
+        Ok(res
+            .into_iter()
+            .map(|(team_id, total_effort)| TeamEffort {
+                team_id,
+                total_effort: total_effort.unwrap_or(0.0),
+            })
+            .collect())
+    }
+
+    /// Count of roles currently marked active, for dashboards.
+    pub async fn count_active(pool: &DbPool) -> Result<i64> {
+        let mut conn = pool.get().await?;
+
+        let count = roles::table
+            .filter(roles::active.eq(true))
+            .count()
+            .get_result(&mut conn)
+            .await?;
+
+        Ok(count)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Insertable, SimpleObject)]
@@ -184,8 +598,11 @@ impl NewRole {
         active: bool,
         start_datestamp: NaiveDateTime,
         end_date: Option<NaiveDateTime>,
-    ) -> Self {
-        NewRole {
+    ) -> Result<Self> {
+        validate_effort(effort)?;
+        validate_date_range(start_datestamp, end_date)?;
+
+        Ok(NewRole {
             person_id,
             team_id,
             title_en,
@@ -194,6 +611,67 @@ impl NewRole {
             active,
             start_datestamp,
             end_date,
-        }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_effort_accepts_the_closed_unit_interval() {
+        assert!(validate_effort(0.0).is_ok());
+        assert!(validate_effort(1.0).is_ok());
+        assert!(validate_effort(0.5).is_ok());
+    }
+
+    #[test]
+    fn validate_effort_rejects_outside_the_unit_interval() {
+        assert!(validate_effort(-0.0001).is_err());
+        assert!(validate_effort(1.0001).is_err());
+    }
+
+    fn datetime(year: i32, month: u32, day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn validate_date_range_accepts_no_end_date() {
+        assert!(validate_date_range(datetime(2026, 1, 1), None).is_ok());
+    }
+
+    #[test]
+    fn validate_date_range_accepts_end_equal_to_start() {
+        let start = datetime(2026, 1, 1);
+        assert!(validate_date_range(start, Some(start)).is_ok());
+    }
+
+    #[test]
+    fn validate_date_range_rejects_end_before_start() {
+        let start = datetime(2026, 1, 2);
+        let end = datetime(2026, 1, 1);
+        assert!(validate_date_range(start, Some(end)).is_err());
+    }
+
+    #[test]
+    fn version_covers_excludes_the_instant_it_closes() {
+        let opened = datetime(2026, 1, 1);
+        let closed = datetime(2026, 1, 10);
+
+        assert!(version_covers(opened, Some(closed), opened));
+        assert!(!version_covers(opened, Some(closed), closed));
+    }
+
+    #[test]
+    fn version_covers_open_ended_version_covers_anything_from_valid_from_onward() {
+        let opened = datetime(2026, 1, 1);
+
+        assert!(!version_covers(opened, None, datetime(2025, 12, 31)));
+        assert!(version_covers(opened, None, opened));
+        assert!(version_covers(opened, None, datetime(2030, 1, 1)));
     }
 }